@@ -0,0 +1,77 @@
+//! Sharing the I2C bus between a PN532 and another peripheral.
+//!
+//! The PN532 sits behind a fixed address (`pn532::i2c::I2C_ADDRESS`), so in
+//! practice it rarely has a bus to itself. Because `I2CInterface` is bound
+//! on the `embedded-hal` 1.0 `I2c` trait, it can be constructed from an
+//! `embedded-hal-bus` `RefCellDevice` instead of taking the bus outright,
+//! letting another driver (here, a stand-in temperature sensor) time-share
+//! the same bus via its own `RefCellDevice` handle.
+#![no_std]
+#![no_main]
+
+use core::cell::RefCell;
+use core::convert::Infallible;
+
+use embedded_hal::i2c::{ErrorType, I2c, Operation};
+use embedded_hal_bus::i2c::RefCellDevice;
+use panic_halt as _;
+use pn532::i2c::{I2CInterface, ReadyTimeout};
+use pn532::Interface;
+
+/// Placeholder for the board's concrete I2C peripheral type, standing in
+/// for whatever your HAL's `I2c` implementation actually is. It does not
+/// talk to real hardware: `transaction` is an inert no-op so this example
+/// keeps looping instead of panicking, but that also means it won't
+/// actually find a PN532. Swap `BoardI2c` for the board's real I2C type
+/// before deploying this.
+struct BoardI2c;
+
+impl ErrorType for BoardI2c {
+    type Error = Infallible;
+}
+
+impl I2c for BoardI2c {
+    fn transaction(
+        &mut self,
+        _address: u8,
+        _operations: &mut [Operation<'_>],
+    ) -> Result<(), Self::Error> {
+        Ok(())
+    }
+}
+
+/// Stand-in for some other I2C peripheral sharing the bus with the PN532,
+/// e.g. a temperature sensor at address `0x76`.
+struct TemperatureSensor<I2C> {
+    i2c: I2C,
+}
+
+impl<I2C: embedded_hal::i2c::I2c> TemperatureSensor<I2C> {
+    const ADDRESS: u8 = 0x76;
+
+    fn read_raw(&mut self) -> Result<[u8; 2], I2C::Error> {
+        let mut buf = [0; 2];
+        self.i2c.read(Self::ADDRESS, &mut buf)?;
+        Ok(buf)
+    }
+}
+
+#[cortex_m_rt::entry]
+fn main() -> ! {
+    let i2c = BoardI2c;
+
+    let bus = RefCell::new(i2c);
+
+    // The PN532 interface and the sensor each get their own handle onto
+    // the shared bus; neither one owns it outright.
+    let mut pn532_interface = I2CInterface::new(RefCellDevice::new(&bus), ReadyTimeout::default());
+
+    let mut temperature_sensor = TemperatureSensor {
+        i2c: RefCellDevice::new(&bus),
+    };
+
+    loop {
+        let _ = pn532_interface.write(&[0x02]);
+        let _ = temperature_sensor.read_raw();
+    }
+}
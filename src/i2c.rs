@@ -1,11 +1,11 @@
 //! I2C interfaces
 
 use core::convert::Infallible;
-use core::fmt::Debug;
 use core::task::Poll;
 
-use embedded_hal::blocking::i2c::{Operation, Read, Transactional, Write};
-use embedded_hal::digital::v2::InputPin;
+use embedded_hal::digital::{ErrorType as DigitalErrorType, InputPin};
+use embedded_hal::i2c::{Error as _, ErrorKind, I2c, Operation};
+use embedded_hal_async::delay::DelayNs;
 
 use crate::{AsyncInterface, Interface};
 
@@ -15,124 +15,355 @@ pub const PN532_I2C_READY: u8 = 0x01;
 /// I2C address of the Pn532
 pub const I2C_ADDRESS: u8 = 0x24;
 
+/// Error returned by the [`Interface`]/[`AsyncInterface`] implementations in this module.
+///
+/// This distinguishes a PN532 that is simply not acknowledging the bus
+/// (not present, unpowered, or not yet ready to respond) from a genuine
+/// bus-level fault, so callers can implement "is the reader even there?"
+/// retry logic without having to inspect the wrapped HAL error.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Pn532I2cError<E> {
+    /// The PN532 did not acknowledge the transfer on address [`I2C_ADDRESS`]
+    NoAcknowledge,
+    /// `wait_ready`'s poll budget was exhausted before the PN532 became ready
+    Timeout,
+    /// Any other bus error, e.g. arbitration loss or a bus overrun
+    Other(E),
+}
+
+/// Classifies an [`embedded_hal::i2c::Error`] into a [`Pn532I2cError`],
+/// mapping `ErrorKind::NoAcknowledge` to the dedicated variant. Used by
+/// both the blocking and async interfaces below, since
+/// `embedded-hal-async` re-exports `embedded-hal`'s I2C error types.
+fn classify<E>(err: E) -> Pn532I2cError<E>
+where
+    E: embedded_hal::i2c::Error,
+{
+    match err.kind() {
+        ErrorKind::NoAcknowledge(_) => Pn532I2cError::NoAcknowledge,
+        _ => Pn532I2cError::Other(err),
+    }
+}
+
+/// Bounds how many times the blocking `Interface::wait_ready` impls in
+/// this module poll the PN532 before giving up with
+/// `Pn532I2cError::Timeout`, so a wedged PN532 can no longer hang a
+/// command sequence forever.
+///
+/// The right `max_polls` depends on how expensive a single poll is:
+/// [`I2CInterface`] polls by issuing an I2C read, so [`ReadyTimeout::default`]
+/// (tuned for that cost) is appropriate there. [`I2CInterfaceWithIrq`] polls
+/// by reading a GPIO pin, which is orders of magnitude cheaper per poll, so
+/// use [`ReadyTimeout::default_for_irq`] instead: reusing the I2C-paced
+/// default there would exhaust the budget in microseconds, long before a
+/// healthy PN532 finishes a slow command (e.g. up to ~1 s for
+/// `InListPassiveTarget`).
+#[derive(Clone, Copy, Debug)]
+pub struct ReadyTimeout {
+    /// Maximum number of `wait_ready` polls before giving up
+    pub max_polls: u32,
+}
+
+impl ReadyTimeout {
+    /// A poll budget tuned for [`I2CInterfaceWithIrq`], where each poll is a
+    /// cheap GPIO read rather than an I2C bus transaction, so it needs a
+    /// much larger count to cover the PN532's slowest commands.
+    pub const fn default_for_irq() -> Self {
+        Self {
+            max_polls: 10_000_000,
+        }
+    }
+}
+
+impl Default for ReadyTimeout {
+    fn default() -> Self {
+        // A single-byte I2C read takes on the order of 0.1-0.2 ms even at
+        // 100 kHz, so 10_000 polls covers ~1-2 s — comfortably above the
+        // ~1 s worst case cited above (e.g. `InListPassiveTarget`) — while
+        // still bounding a wedged device.
+        Self { max_polls: 10_000 }
+    }
+}
+
+/// A [`DelayNs`](embedded_hal_async::delay::DelayNs) impl that does not
+/// actually delay, used as the default [`AsyncReadyTimeout`] delay so
+/// callers are not forced to thread one through when no backoff is
+/// wanted.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NoDelay;
+
+impl DelayNs for NoDelay {
+    async fn delay_ns(&mut self, _ns: u32) {}
+}
+
+/// Bounds how many times `AsyncI2cInterface::wait_ready` polls the PN532
+/// before giving up with `Pn532I2cError::Timeout`, with an optional
+/// delay inserted between polls to back off instead of hammering the bus.
+#[derive(Clone, Copy, Debug)]
+pub struct AsyncReadyTimeout<D = NoDelay> {
+    /// Maximum number of `wait_ready` polls before giving up
+    pub max_polls: u32,
+    /// Delay inserted between polls, in nanoseconds
+    pub backoff_ns: u32,
+    /// Delay implementation used to wait `backoff_ns` between polls
+    pub delay: D,
+}
+
+impl Default for AsyncReadyTimeout {
+    fn default() -> Self {
+        // `NoDelay` makes `backoff_ns` a no-op, so with the default delay
+        // the budget is covered by read cost alone: as with
+        // `ReadyTimeout::default`, 10_000 reads covers ~1-2 s, comfortably
+        // above the ~1 s worst case cited above. `backoff_ns` is set to a
+        // sensible 1 ms so that swapping in a real `DelayNs` impl backs off
+        // between polls instead of hammering the bus, without having to
+        // pick a value from scratch.
+        Self {
+            max_polls: 10_000,
+            backoff_ns: 1_000_000,
+            delay: NoDelay,
+        }
+    }
+}
+
 /// I2C Interface without IRQ pin
+///
+/// `I2C` only needs to implement the `embedded-hal` 1.0 [`I2c`] trait, so
+/// besides a bus owned outright, this also accepts a shared-bus wrapper
+/// such as [`embedded_hal_bus::i2c::RefCellDevice`] or `AtomicDevice`,
+/// letting the PN532 coexist with other devices on the same bus.
 #[derive(Clone, Debug)]
 pub struct I2CInterface<I2C>
 where
-    I2C: Transactional,
-    I2C: Write<Error = <I2C as Transactional>::Error>,
-    I2C: Read<Error = <I2C as Transactional>::Error>,
-    <I2C as Transactional>::Error: Debug,
+    I2C: I2c,
 {
     pub i2c: I2C,
+    /// Bounds how many times `wait_ready` polls before giving up
+    pub ready_timeout: ReadyTimeout,
+    /// Number of polls performed since the PN532 was last seen ready;
+    /// reset automatically once it becomes ready or the budget is spent
+    polls_elapsed: u32,
+}
+
+impl<I2C> I2CInterface<I2C>
+where
+    I2C: I2c,
+{
+    /// Creates a new `I2CInterface` with the given poll budget
+    pub fn new(i2c: I2C, ready_timeout: ReadyTimeout) -> Self {
+        Self {
+            i2c,
+            ready_timeout,
+            polls_elapsed: 0,
+        }
+    }
 }
 
 impl<I2C> Interface for I2CInterface<I2C>
 where
-    I2C: Transactional,
-    I2C: Write<Error = <I2C as Transactional>::Error>,
-    I2C: Read<Error = <I2C as Transactional>::Error>,
-    <I2C as Transactional>::Error: Debug,
+    I2C: I2c,
 {
-    type Error = <I2C as Transactional>::Error;
+    type Error = Pn532I2cError<I2C::Error>;
 
     fn write(&mut self, frame: &[u8]) -> Result<(), Self::Error> {
-        self.i2c.write(I2C_ADDRESS, frame)
+        self.i2c.write(I2C_ADDRESS, frame).map_err(classify)
     }
 
     fn wait_ready(&mut self) -> Poll<Result<(), Self::Error>> {
         let mut buf = [0];
-        self.i2c.read(I2C_ADDRESS, &mut buf)?;
+        if let Err(err) = self.i2c.read(I2C_ADDRESS, &mut buf) {
+            self.polls_elapsed = 0;
+            return Poll::Ready(Err(classify(err)));
+        }
 
         if buf[0] == PN532_I2C_READY {
-            Poll::Ready(Ok(()))
-        } else {
-            Poll::Pending
+            self.polls_elapsed = 0;
+            return Poll::Ready(Ok(()));
+        }
+
+        self.polls_elapsed += 1;
+        if self.polls_elapsed >= self.ready_timeout.max_polls {
+            self.polls_elapsed = 0;
+            return Poll::Ready(Err(Pn532I2cError::Timeout));
         }
+
+        Poll::Pending
     }
 
     fn read(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
-        self.i2c.exec(
-            I2C_ADDRESS,
-            &mut [Operation::Read(&mut [0]), Operation::Read(buf)],
-        )
+        self.i2c
+            .transaction(
+                I2C_ADDRESS,
+                &mut [Operation::Read(&mut [0]), Operation::Read(buf)],
+            )
+            .map_err(classify)
     }
 }
 
 /// I2C Interface with IRQ pin
+///
+/// As with [`I2CInterface`], `I2C` only needs to implement the
+/// `embedded-hal` 1.0 [`I2c`] trait, so a shared-bus wrapper works here
+/// too.
 #[derive(Clone, Debug)]
 pub struct I2CInterfaceWithIrq<I2C, IRQ>
 where
-    I2C: Transactional,
-    I2C: Write<Error = <I2C as Transactional>::Error>,
-    I2C: Read<Error = <I2C as Transactional>::Error>,
-    <I2C as Transactional>::Error: Debug,
-    IRQ: InputPin<Error = Infallible>,
+    I2C: I2c,
+    IRQ: InputPin,
+    IRQ: DigitalErrorType<Error = Infallible>,
 {
     pub i2c: I2C,
     pub irq: IRQ,
+    /// Bounds how many times `wait_ready` polls before giving up; use
+    /// [`ReadyTimeout::default_for_irq`] rather than [`ReadyTimeout::default`]
+    pub ready_timeout: ReadyTimeout,
+    /// Number of polls performed since the PN532 was last seen ready;
+    /// reset automatically once it becomes ready or the budget is spent
+    polls_elapsed: u32,
+}
+
+impl<I2C, IRQ> I2CInterfaceWithIrq<I2C, IRQ>
+where
+    I2C: I2c,
+    IRQ: InputPin,
+    IRQ: DigitalErrorType<Error = Infallible>,
+{
+    /// Creates a new `I2CInterfaceWithIrq` with the given poll budget
+    pub fn new(i2c: I2C, irq: IRQ, ready_timeout: ReadyTimeout) -> Self {
+        Self {
+            i2c,
+            irq,
+            ready_timeout,
+            polls_elapsed: 0,
+        }
+    }
 }
 
 impl<I2C, IRQ> Interface for I2CInterfaceWithIrq<I2C, IRQ>
 where
-    I2C: Transactional,
-    I2C: Write<Error = <I2C as Transactional>::Error>,
-    I2C: Read<Error = <I2C as Transactional>::Error>,
-    <I2C as Transactional>::Error: Debug,
-    IRQ: InputPin<Error = Infallible>,
+    I2C: I2c,
+    IRQ: InputPin,
+    IRQ: DigitalErrorType<Error = Infallible>,
 {
-    type Error = <I2C as Transactional>::Error;
+    type Error = Pn532I2cError<I2C::Error>;
 
     fn write(&mut self, frame: &[u8]) -> Result<(), Self::Error> {
-        self.i2c.write(I2C_ADDRESS, frame)
+        self.i2c.write(I2C_ADDRESS, frame).map_err(classify)
     }
 
     fn wait_ready(&mut self) -> Poll<Result<(), Self::Error>> {
         // infallible unwrap because of IRQ bound
         if self.irq.is_low().unwrap() {
-            Poll::Ready(Ok(()))
-        } else {
-            Poll::Pending
+            self.polls_elapsed = 0;
+            return Poll::Ready(Ok(()));
+        }
+
+        self.polls_elapsed += 1;
+        if self.polls_elapsed >= self.ready_timeout.max_polls {
+            self.polls_elapsed = 0;
+            return Poll::Ready(Err(Pn532I2cError::Timeout));
         }
+
+        Poll::Pending
     }
 
     fn read(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
-        self.i2c.exec(
-            I2C_ADDRESS,
-            &mut [Operation::Read(&mut [0]), Operation::Read(buf)],
-        )
+        self.i2c
+            .transaction(
+                I2C_ADDRESS,
+                &mut [Operation::Read(&mut [0]), Operation::Read(buf)],
+            )
+            .map_err(classify)
     }
 }
 
-pub struct AsyncI2cInterface<I2C>
+pub struct AsyncI2cInterface<I2C, D = NoDelay>
 where
     I2C: embedded_hal_async::i2c::I2c,
 {
     pub i2c: I2C,
+    /// Bounds how many times `wait_ready` polls before giving up, with an
+    /// optional backoff delay between polls
+    pub ready_timeout: AsyncReadyTimeout<D>,
 }
 
-impl<I2C> AsyncInterface for AsyncI2cInterface<I2C>
+impl<I2C, D> AsyncInterface for AsyncI2cInterface<I2C, D>
 where
     I2C: embedded_hal_async::i2c::I2c,
+    D: DelayNs,
 {
-    type Error = I2C::Error;
+    type Error = Pn532I2cError<I2C::Error>;
 
     async fn write(&mut self, frame: &[u8]) -> Result<(), Self::Error> {
-        self.i2c.write(I2C_ADDRESS, frame).await
+        self.i2c.write(I2C_ADDRESS, frame).await.map_err(classify)
     }
 
     async fn wait_ready(&mut self) -> Result<(), Self::Error> {
         let mut buf = [0];
 
-        loop {
-            self.i2c.read(I2C_ADDRESS, &mut buf).await?;
+        for _ in 0..self.ready_timeout.max_polls {
+            self.i2c
+                .read(I2C_ADDRESS, &mut buf)
+                .await
+                .map_err(classify)?;
             if buf[0] == PN532_I2C_READY {
                 return Ok(());
             }
+            self.ready_timeout
+                .delay
+                .delay_ns(self.ready_timeout.backoff_ns)
+                .await;
         }
+
+        Err(Pn532I2cError::Timeout)
+    }
+
+    async fn read(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
+        self.i2c.read(I2C_ADDRESS, buf).await.map_err(classify)
+    }
+}
+
+/// Async I2C Interface with IRQ pin
+///
+/// Rather than polling the status byte, `wait_ready` awaits `irq` being
+/// low (returning at once if it already is), letting the executor sleep
+/// the task until the PN532 signals that it is ready.
+///
+/// Unlike the polling interfaces in this module, `wait_ready` here has no
+/// poll budget of its own: it is a single `await` on the pin, not a loop,
+/// so there is nothing to count. This is intentionally unbounded — a
+/// wedged PN532 that never pulls IRQ low parks the task rather than
+/// spinning, and async executors already have a standard way to bound an
+/// await (e.g. `embassy_time::with_timeout`), so callers who need a
+/// deadline should wrap the call with their runtime's own timeout instead
+/// of this interface reimplementing one.
+pub struct AsyncI2cInterfaceWithIrq<I2C, IRQ>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+    IRQ: embedded_hal_async::digital::Wait<Error = Infallible>,
+{
+    pub i2c: I2C,
+    pub irq: IRQ,
+}
+
+impl<I2C, IRQ> AsyncInterface for AsyncI2cInterfaceWithIrq<I2C, IRQ>
+where
+    I2C: embedded_hal_async::i2c::I2c,
+    IRQ: embedded_hal_async::digital::Wait<Error = Infallible>,
+{
+    type Error = Pn532I2cError<I2C::Error>;
+
+    async fn write(&mut self, frame: &[u8]) -> Result<(), Self::Error> {
+        self.i2c.write(I2C_ADDRESS, frame).await.map_err(classify)
+    }
+
+    async fn wait_ready(&mut self) -> Result<(), Self::Error> {
+        // infallible unwrap because of IRQ bound
+        self.irq.wait_for_low().await.unwrap();
+        Ok(())
     }
 
     async fn read(&mut self, buf: &mut [u8]) -> Result<(), Self::Error> {
-        self.i2c.read(I2C_ADDRESS, buf).await
+        self.i2c.read(I2C_ADDRESS, buf).await.map_err(classify)
     }
 }